@@ -1,4 +1,5 @@
-use std::ops::{Add, BitOrAssign};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug)]
 enum Instruction {
@@ -6,26 +7,113 @@ enum Instruction {
     Add,
     Jmpos(i32),
     Ret,
+    Set(char, Value),
+    Sub(char, Value),
+    Mul(char, Value),
+    Mod(char, Value),
+    Jgz(Value, i32),
+    Jnz(Value, i32),
+    Snd(Value),
+    Rcv(char),
 }
 
+// A line that couldn't be turned into an `Instruction`, carrying the
+// offending line alongside the reason it was rejected.
+#[derive(Debug)]
+struct ParseError {
+    line: String,
+    reason: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to parse {:?}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl Instruction {
-    fn from_str(input: &str) -> Self {
+    fn from_str(input: &str) -> Result<Self, ParseError> {
+        Self::parse(input).map_err(|reason| ParseError {
+            line: input.to_string(),
+            reason,
+        })
+    }
+
+    fn parse(input: &str) -> Result<Self, String> {
         let mut split_input = input.split(' ');
-        match split_input.next().unwrap() {
-            "push" => Self::Push(
-                match split_input.next().unwrap().to_ascii_lowercase().as_str() {
-                    "x" => Value::X,
-                    "y" => Value::Y,
-                    "z" => Value::Z,
-                    v => Value::Num(v.parse().unwrap()),
-                },
-            ),
+        let opcode = split_input.next().ok_or("missing opcode")?;
+        let mut operand = || split_input.next().ok_or("missing operand".to_string());
+
+        Ok(match opcode {
+            "push" => Self::Push(parse_value(operand()?)?),
             "add" => Self::Add,
-            "jmpos" => Self::Jmpos(split_input.next().unwrap().parse().unwrap()),
+            "jmpos" => Self::Jmpos(parse_offset(operand()?)?),
             "ret" => Self::Ret,
-            v => panic!("Unrecognised instruction: {}", v),
+            "set" => Self::Set(parse_register(operand()?)?, parse_value(operand()?)?),
+            "sub" => Self::Sub(parse_register(operand()?)?, parse_value(operand()?)?),
+            "mul" => Self::Mul(parse_register(operand()?)?, parse_value(operand()?)?),
+            "mod" => Self::Mod(parse_register(operand()?)?, parse_value(operand()?)?),
+            "jgz" => Self::Jgz(parse_value(operand()?)?, parse_offset(operand()?)?),
+            "jnz" => Self::Jnz(parse_value(operand()?)?, parse_offset(operand()?)?),
+            "snd" => Self::Snd(parse_value(operand()?)?),
+            "rcv" => Self::Rcv(parse_register(operand()?)?),
+            v => return Err(format!("unrecognised instruction: {v}")),
+        })
+    }
+}
+
+// A bare register name is a single ASCII letter (matched case-insensitively,
+// like the coordinate inputs) that isn't one of the seeded coordinate
+// inputs; anything else is parsed as an immediate.
+fn parse_value(input: &str) -> Result<Value, String> {
+    Ok(match input.to_ascii_lowercase().as_str() {
+        "x" => Value::X,
+        "y" => Value::Y,
+        "z" => Value::Z,
+        v if v.len() == 1 && v.chars().next().unwrap().is_ascii_lowercase() => {
+            Value::Reg(v.chars().next().unwrap())
         }
+        v => Value::Num(parse_number(v)?),
+    })
+}
+
+fn parse_register(input: &str) -> Result<char, String> {
+    let mut chars = input.chars();
+    let reg = chars.next().ok_or("missing register")?;
+    if chars.next().is_some() || !reg.is_ascii_lowercase() {
+        return Err(format!("invalid register {:?}", input));
     }
+    Ok(reg)
+}
+
+fn parse_offset(input: &str) -> Result<i32, String> {
+    let value = parse_number(input)?;
+    i32::try_from(value).map_err(|_| format!("jump offset {:?} out of range", input))
+}
+
+// Shared numeric grammar for operands: plain signed decimals, plus `0x`/
+// `0b`/`0o`-prefixed hex/binary/octal literals (sign goes before the prefix).
+fn parse_number(input: &str) -> Result<i64, String> {
+    let (negative, unsigned) = match input.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+
+    let (radix, digits) = if let Some(digits) = unsigned.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = unsigned.strip_prefix("0b") {
+        (2, digits)
+    } else if let Some(digits) = unsigned.strip_prefix("0o") {
+        (8, digits)
+    } else {
+        (10, unsigned)
+    };
+
+    let value = i64::from_str_radix(digits, radix)
+        .map_err(|_| format!("invalid numeric literal {:?}", input))?;
+    Ok(if negative { -value } else { value })
 }
 
 #[derive(Debug)]
@@ -33,7 +121,8 @@ enum Value {
     X,
     Y,
     Z,
-    Num(i32),
+    Num(i64),
+    Reg(char),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -43,54 +132,273 @@ struct Point {
     z: i32,
 }
 
-fn run_program(program: &[Instruction], point: Point) -> i32 {
-    let mut pc = 0;
-    let mut stack = vec![];
+fn eval_value(value: &Value, point: Point, registers: &HashMap<char, i64>) -> i64 {
+    match value {
+        Value::X => point.x as i64,
+        Value::Y => point.y as i64,
+        Value::Z => point.z as i64,
+        Value::Num(v) => *v,
+        Value::Reg(r) => *registers.get(r).unwrap_or(&0),
+    }
+}
 
-    loop {
-        // dbg!(pc, &program[pc as usize], &stack);
-        match &program[pc as usize] {
-            Instruction::Push(value) => match value {
-                Value::X => stack.push(point.x),
-                Value::Y => stack.push(point.y),
-                Value::Z => stack.push(point.z),
-                Value::Num(v) => stack.push(*v),
-            },
-            Instruction::Add => {
-                let x = stack.pop().unwrap();
-                let y = stack.pop().unwrap();
-                stack.push(x + y);
+// One running copy of a program: its own program counter, stack, registers
+// and inbox. `run_program` drives a single instance to completion; `run_duet`
+// interleaves two of them over `snd`/`rcv`.
+#[derive(Debug, Default)]
+struct ProcessState {
+    pc: i32,
+    stack: Vec<i64>,
+    registers: HashMap<char, i64>,
+    queue: VecDeque<i64>,
+    sent: usize,
+    halted: bool,
+    return_value: Option<i64>,
+}
+
+// Why `run_program` returns a `Result` instead of panicking: a malformed
+// input program used to take down the whole 27,000-cell evaluation.
+#[derive(Debug)]
+enum VmError {
+    StackUnderflow,
+    PcOutOfBounds(i32),
+    StepLimitExceeded(u64),
+    ReceiveOnEmptyQueue,
+    DivideByZero,
+    Overflow,
+}
+
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::StackUnderflow => write!(f, "stack underflow"),
+            VmError::PcOutOfBounds(pc) => write!(f, "program counter {pc} out of bounds"),
+            VmError::StepLimitExceeded(steps) => {
+                write!(f, "stopped after {steps} steps (possible infinite loop)")
             }
-            Instruction::Jmpos(v) => {
-                if stack.pop().unwrap() >= 0 {
-                    pc += v
-                }
+            VmError::ReceiveOnEmptyQueue => write!(f, "rcv on empty queue"),
+            VmError::DivideByZero => write!(f, "mod by zero"),
+            VmError::Overflow => write!(f, "arithmetic overflow"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+// A generous default so pathological programs still terminate promptly;
+// override via `run_program_with_limit` if a program legitimately needs more.
+const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+// Executes the instruction at `state.pc`, advancing `state.pc` (except for
+// `Ret`, which halts instead). `snd` enqueues onto `other_queue`; `rcv`
+// returns `VmError::ReceiveOnEmptyQueue` if `state.queue` is empty rather
+// than blocking, since only `run_duet` knows how to wait for more input.
+fn execute_instruction(
+    program: &[Instruction],
+    point: Point,
+    state: &mut ProcessState,
+    other_queue: &mut VecDeque<i64>,
+) -> Result<(), VmError> {
+    if state.pc < 0 || state.pc as usize >= program.len() {
+        return Err(VmError::PcOutOfBounds(state.pc));
+    }
+
+    match &program[state.pc as usize] {
+        Instruction::Push(value) => state.stack.push(eval_value(value, point, &state.registers)),
+        Instruction::Add => {
+            let x = state.stack.pop().ok_or(VmError::StackUnderflow)?;
+            let y = state.stack.pop().ok_or(VmError::StackUnderflow)?;
+            state.stack.push(x.checked_add(y).ok_or(VmError::Overflow)?);
+        }
+        Instruction::Jmpos(v) => {
+            if state.stack.pop().ok_or(VmError::StackUnderflow)? >= 0 {
+                state.pc += v
+            }
+        }
+        Instruction::Ret => {
+            state.return_value = state.stack.pop();
+            state.halted = true;
+            return Ok(());
+        }
+        Instruction::Set(r, v) => {
+            let value = eval_value(v, point, &state.registers);
+            state.registers.insert(*r, value);
+        }
+        Instruction::Sub(r, v) => {
+            let value = eval_value(v, point, &state.registers);
+            let entry = state.registers.entry(*r).or_insert(0);
+            *entry = entry.checked_sub(value).ok_or(VmError::Overflow)?;
+        }
+        Instruction::Mul(r, v) => {
+            let value = eval_value(v, point, &state.registers);
+            let entry = state.registers.entry(*r).or_insert(0);
+            *entry = entry.checked_mul(value).ok_or(VmError::Overflow)?;
+        }
+        Instruction::Mod(r, v) => {
+            let value = eval_value(v, point, &state.registers);
+            let entry = state.registers.entry(*r).or_insert(0);
+            let err = if value == 0 {
+                VmError::DivideByZero
+            } else {
+                VmError::Overflow
+            };
+            *entry = entry.checked_rem(value).ok_or(err)?;
+        }
+        Instruction::Jgz(v, off) => {
+            if eval_value(v, point, &state.registers) > 0 {
+                state.pc += off
+            }
+        }
+        Instruction::Jnz(v, off) => {
+            if eval_value(v, point, &state.registers) != 0 {
+                state.pc += off
             }
-            Instruction::Ret => return stack.pop().unwrap(),
         }
-        pc += 1;
+        Instruction::Snd(v) => {
+            other_queue.push_back(eval_value(v, point, &state.registers));
+            state.sent += 1;
+        }
+        Instruction::Rcv(r) => {
+            let value = state.queue.pop_front().ok_or(VmError::ReceiveOnEmptyQueue)?;
+            state.registers.insert(*r, value);
+        }
     }
+    state.pc += 1;
+    Ok(())
 }
 
-impl Add for Point {
-    type Output = Option<Point>;
+// Cycle detection isn't worth it for the common case: most programs here
+// either halt in a handful of steps or grow the stack monotonically (in
+// which case it can never repeat a state anyway), and re-hashing the whole
+// stack every step to check is O(steps) per step across 27,000 grid cells.
+// `run_program` relies on `max_steps` alone; callers that know their
+// program can spin on a fixed-size state (e.g. a register-only loop) can
+// opt in via `run_program_with_limit` directly.
+fn run_program(program: &[Instruction], point: Point) -> Result<i64, VmError> {
+    run_program_with_limit(program, point, DEFAULT_MAX_STEPS, false)
+}
 
-    fn add(self, rhs: Self) -> Self::Output {
-        let new_point = Point {
-            x: rhs.x + self.x,
-            y: rhs.y + self.y,
-            z: rhs.z + self.z,
-        };
-        if new_point.x < 0 || new_point.x >= 30 {
-            return None;
+// Hashes the parts of `state` that affect future execution: the program
+// counter, the stack, and the registers (sorted, since `HashMap` iteration
+// order isn't stable). Two equal fingerprints mean the program is in the
+// exact same state it was in before, so it's cycling.
+fn state_fingerprint(state: &ProcessState) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    state.pc.hash(&mut hasher);
+    state.stack.hash(&mut hasher);
+    let mut registers: Vec<(char, i64)> = state.registers.iter().map(|(&r, &v)| (r, v)).collect();
+    registers.sort_unstable();
+    registers.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Steps the program until it hits `ret`, bailing with `StepLimitExceeded`
+// if `max_steps` is reached. When `detect_cycles` is set, also bails early
+// if `state_fingerprint` repeats, which means it's cycling and will never
+// reach `ret` on its own; leave this off unless the program is known to
+// revisit the same (pc, stack, registers) state, since hashing the stack
+// every step is only cheap for programs that don't grow it.
+fn run_program_with_limit(
+    program: &[Instruction],
+    point: Point,
+    max_steps: u64,
+    detect_cycles: bool,
+) -> Result<i64, VmError> {
+    let mut state = ProcessState::default();
+    let mut scratch_queue = VecDeque::new();
+    let mut seen_states: HashSet<u64> = HashSet::new();
+
+    for step in 0..max_steps {
+        if detect_cycles && !seen_states.insert(state_fingerprint(&state)) {
+            return Err(VmError::StepLimitExceeded(step));
+        }
+        execute_instruction(program, point, &mut state, &mut scratch_queue)?;
+        if state.halted {
+            return state.return_value.ok_or(VmError::StackUnderflow);
         }
-        if new_point.y < 0 || new_point.y >= 30 {
-            return None;
+    }
+    Err(VmError::StepLimitExceeded(max_steps))
+}
+
+#[derive(Debug)]
+struct DuetResult {
+    sent: [usize; 2],
+}
+
+// Whether `state` cannot make progress right now: halted, its pc ran off
+// the program, or it's sitting on a `rcv` with nothing queued for it.
+fn program_blocked(program: &[Instruction], state: &ProcessState) -> bool {
+    let pc_in_bounds = state.pc >= 0 && (state.pc as usize) < program.len();
+    state.halted
+        || !pc_in_bounds
+        || (matches!(program[state.pc as usize], Instruction::Rcv(_)) && state.queue.is_empty())
+}
+
+// Runs two copies of `program` concurrently, each with its own id register
+// `p` (0 and 1). Program 0 steps until it blocks on an empty `rcv`, then
+// program 1 takes over, and so on; if both are blocked (or halted) at the
+// same time, that's a deadlock and we report how many values each sent.
+fn run_duet(program: &[Instruction]) -> DuetResult {
+    let mut states = [ProcessState::default(), ProcessState::default()];
+    states[0].registers.insert('p', 0);
+    states[1].registers.insert('p', 1);
+    let mut current = 0usize;
+
+    loop {
+        if program_blocked(program, &states[current]) {
+            if program_blocked(program, &states[1 - current]) {
+                break;
+            }
+            current = 1 - current;
+            continue;
         }
-        if new_point.z < 0 || new_point.z >= 30 {
-            return None;
+
+        let (left, right) = states.split_at_mut(1);
+        let (this_state, other_state) = if current == 0 {
+            (&mut left[0], &mut right[0])
+        } else {
+            (&mut right[0], &mut left[0])
+        };
+        if execute_instruction(
+            program,
+            Point::new(0, 0, 0),
+            this_state,
+            &mut other_state.queue,
+        )
+        .is_err()
+        {
+            // A program that can't execute its next instruction isn't going
+            // to unblock the other one either; treat it as halted.
+            this_state.halted = true;
         }
-        Some(new_point)
+    }
+
+    DuetResult {
+        sent: [states[0].sent, states[1].sent],
+    }
+}
+
+// The dimensions of the region being solved over, so the same solver can
+// run on different (and non-cubic) puzzle inputs instead of a fixed 30^3.
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    x: i32,
+    y: i32,
+    z: i32,
+}
+
+impl Bounds {
+    fn contains(&self, point: Point) -> bool {
+        point.x >= 0
+            && point.x < self.x
+            && point.y >= 0
+            && point.y < self.y
+            && point.z >= 0
+            && point.z < self.z
+    }
+
+    fn volume(&self) -> usize {
+        self.x as usize * self.y as usize * self.z as usize
     }
 }
 
@@ -99,12 +407,20 @@ impl Point {
         Self { x, y, z }
     }
 
-    fn index<'a, T>(&self, grid: &'a [[[T; 30]; 30]; 30]) -> &'a T {
-        &grid[self.x as usize][self.y as usize][self.z as usize]
+    // Adds `rhs` to `self`, or `None` if the result would fall outside `bounds`.
+    fn add(self, rhs: Point, bounds: Bounds) -> Option<Point> {
+        let new_point = Point {
+            x: self.x + rhs.x,
+            y: self.y + rhs.y,
+            z: self.z + rhs.z,
+        };
+        bounds.contains(new_point).then_some(new_point)
     }
 
-    fn index_mut<'a, T>(&self, grid: &'a mut [[[T; 30]; 30]; 30]) -> &'a mut T {
-        &mut grid[self.x as usize][self.y as usize][self.z as usize]
+    // Flattened offset into a `bounds.x * bounds.y * bounds.z` flat grid.
+    fn index(self, bounds: Bounds) -> usize {
+        (self.x as usize * bounds.y as usize + self.y as usize) * bounds.z as usize
+            + self.z as usize
     }
 }
 
@@ -117,83 +433,162 @@ const CARDINALS: [Point; 6] = [
     Point { x: 0, y: 0, z: -1 },
 ];
 
-fn grow_cloud(
-    grid: &[[[bool; 30]; 30]; 30],
-    considered: &mut [[[bool; 30]; 30]; 30],
-    point: Point,
-) -> Vec<Point> {
-    let mut included = vec![];
-    if !*point.index(grid) {
-        return included;
-    }
-
-    // New point is part of a cloud
-    included.push(point);
-    for offset in CARDINALS {
-        let new_point = point + offset;
-        let new_point = match new_point {
-            Some(np) => np,
-            None => {
-                // The point where we would grow to is out of range.
-                continue;
+// Disjoint-set over flattened grid ids, with path halving and union by rank.
+struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        Self {
+            parent: (0..size).collect(),
+            rank: vec![0; size],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        if root_a == root_b {
+            return;
+        }
+        match self.rank[root_a].cmp(&self.rank[root_b]) {
+            std::cmp::Ordering::Less => self.parent[root_a] = root_b,
+            std::cmp::Ordering::Greater => self.parent[root_b] = root_a,
+            std::cmp::Ordering::Equal => {
+                self.parent[root_b] = root_a;
+                self.rank[root_a] += 1;
+            }
+        }
+    }
+}
+
+// Iteratively unions every filled cell with its filled `CARDINALS`
+// neighbours in a single sweep, then returns the size of each resulting
+// cloud. Unlike a recursive flood fill, this never risks a stack overflow
+// on large or dense grids.
+fn cloud_sizes(grid: &[bool], bounds: Bounds) -> Vec<usize> {
+    let mut sets = UnionFind::new(bounds.volume());
+
+    for x in 0..bounds.x {
+        for y in 0..bounds.y {
+            for z in 0..bounds.z {
+                let point = Point::new(x, y, z);
+                if !grid[point.index(bounds)] {
+                    continue;
+                }
+                for offset in CARDINALS {
+                    let Some(neighbour) = point.add(offset, bounds) else {
+                        continue;
+                    };
+                    if grid[neighbour.index(bounds)] {
+                        sets.union(point.index(bounds), neighbour.index(bounds));
+                    }
+                }
             }
-        };
-        if *new_point.index(considered) {
-            // We've already considered this point, move on.
-            continue;
         }
-        // Regardless of whether it's in this a cloud or not, we've considered it now.
-        new_point.index_mut(considered).bitor_assign(true);
-        if *new_point.index(grid) {
-            // New point is part of the cloud
-            included.append(&mut grow_cloud(grid, considered, new_point));
+    }
+
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for x in 0..bounds.x {
+        for y in 0..bounds.y {
+            for z in 0..bounds.z {
+                let point = Point::new(x, y, z);
+                if grid[point.index(bounds)] {
+                    *sizes.entry(sets.find(point.index(bounds))).or_insert(0) += 1;
+                }
+            }
         }
     }
 
-    included
+    sizes.into_values().collect()
+}
+
+fn largest_cloud(sizes: &[usize]) -> usize {
+    sizes.iter().copied().max().unwrap_or(0)
+}
+
+fn total_filled_volume(sizes: &[usize]) -> usize {
+    sizes.iter().sum()
 }
 
 fn main() {
     let program_text = include_str!("../input_program.txt");
 
-    let program: Vec<Instruction> = program_text.lines().map(Instruction::from_str).collect();
+    let program: Vec<Instruction> = match program_text.lines().map(Instruction::from_str).collect()
+    {
+        Ok(program) => program,
+        Err(err) => panic!("failed to parse program: {err}"),
+    };
 
-    let mut grid = [[[false; 30]; 30]; 30];
+    let bounds = Bounds { x: 30, y: 30, z: 30 };
+    let mut grid = vec![false; bounds.volume()];
 
-    let mut calibration_number = 0;
-    for x in 0..30 {
-        for y in 0..30 {
-            for z in 0..30 {
-                let grid_value = run_program(&program, Point::new(x, y, z));
-                calibration_number += grid_value;
-                if grid_value > 0 {
-                    grid[x as usize][y as usize][z as usize] = true;
+    let mut calibration_number: i64 = 0;
+    for x in 0..bounds.x {
+        for y in 0..bounds.y {
+            for z in 0..bounds.z {
+                let point = Point::new(x, y, z);
+                match run_program(&program, point) {
+                    Ok(grid_value) => {
+                        calibration_number += grid_value;
+                        if grid_value > 0 {
+                            grid[point.index(bounds)] = true;
+                        }
+                    }
+                    Err(err) => eprintln!("error running program at {point:?}: {err}"),
                 }
             }
         }
     }
     println!("Calibration number: {calibration_number}");
 
-    let mut clouds = 0;
-    let mut considered_points = [[[false; 30]; 30]; 30];
-    for x in 0..30 {
-        for y in 0..30 {
-            for z in 0..30 {
-                if considered_points[x][y][z] {
-                    // We've already considered this point, move on.
-                    continue;
-                }
-                considered_points[x][y][z] = true;
-                let cloud = grow_cloud(
-                    &grid,
-                    &mut considered_points,
-                    Point::new(x as i32, y as i32, z as i32),
-                );
-                if !cloud.is_empty() {
-                    clouds += 1;
-                }
-            }
-        }
+    let duet_result = run_duet(&program);
+    println!("Program 1 sent: {}", duet_result.sent[1]);
+
+    let sizes = cloud_sizes(&grid, bounds);
+    println!("Clouds: {}", sizes.len());
+    println!("Largest cloud: {}", largest_cloud(&sizes));
+    println!("Total filled volume: {}", total_filled_volume(&sizes));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(lines: &[&str]) -> Vec<Instruction> {
+        lines
+            .iter()
+            .map(|line| Instruction::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn duet_counts_every_send_even_after_unblocking_the_other_side() {
+        // Both programs ping-pong twice before ret-ing; program 0 blocks on
+        // its first `rcv`, but program 1's `snd` unblocks it again, so
+        // neither side should stop short.
+        let program = assemble(&["snd 1", "rcv a", "snd 2", "rcv b", "ret"]);
+        let result = run_duet(&program);
+        assert_eq!(result.sent, [2, 2]);
+    }
+
+    #[test]
+    fn register_loop_terminates_instead_of_hitting_the_step_limit() {
+        // `a` keeps changing every pass through the loop, so a fingerprint
+        // that ignored registers would see the same `(pc, stack)` twice and
+        // wrongly report a cycle before the loop naturally runs out. Opt
+        // into cycle detection explicitly since it's off by default.
+        let program = assemble(&["set a 5", "sub a 1", "jgz a -2", "push a", "ret"]);
+        let result = run_program_with_limit(&program, Point::new(0, 0, 0), DEFAULT_MAX_STEPS, true);
+        assert_eq!(result.unwrap(), 0);
     }
-    println!("Clouds: {clouds}");
 }